@@ -1,8 +1,36 @@
+// This crate assumes the `atomics`/`bulk-memory` target features (e.g.
+// `RUSTFLAGS="-C target-feature=+atomics,+bulk-memory"` with `-Z build-std`) and a
+// SharedArrayBuffer-capable host. Building without them silently compiles a module that can
+// never join a Rayon thread pool, which only shows up as a cryptic failure at run time -
+// fail the build instead. (Host support, which can't be known at compile time, is covered by
+// the runtime `isThreadingSupported` check and `init_thread_pool`'s single-threaded fallback.)
+#[cfg(all(not(doc), target_arch = "wasm32", not(target_feature = "atomics")))]
+compile_error!(
+    "wasm-worker-threads must be built with the `atomics` and `bulk-memory` target features, e.g. \
+     RUSTFLAGS=\"-C target-feature=+atomics,+bulk-memory\" cargo build -Z build-std=panic_unwind,std --target wasm32-unknown-unknown"
+);
+
+// `threadpool_manager::run_in_pool`/`spawn_async` recover from worker panics with
+// `std::panic::catch_unwind`, which cannot intercept anything under `panic = "abort"` - on
+// wasm32 that strategy traps the whole instance the instant a job panics, exactly the failure
+// mode panic recovery is meant to prevent. Build std with `panic_unwind` rather than
+// `panic_abort` (see the RUSTFLAGS/`-Z build-std` invocation above).
+#[cfg(all(not(doc), target_arch = "wasm32", panic = "abort"))]
+compile_error!(
+    "wasm-worker-threads relies on catch_unwind to recover from worker panics, which requires \
+     the `unwind` panic strategy; build with `-Z build-std=panic_unwind,std` instead of \
+     `panic_abort`"
+);
+
 mod threadpool_manager;
 
 use console_error_panic_hook;
+use js_sys::Promise;
 use rayon::current_thread_index;
 use rayon::prelude::*;
+// re-exported so integration tests (and any other Rust-side consumer) can name the handle
+// type and call the entrypoints that hand one out, not just the wasm-bindgen JS bindings
+pub use threadpool_manager::{init_thread_pool, init_thread_pool_auto, ThreadPoolHandle};
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsValue;
 
@@ -28,18 +56,53 @@ pub fn main_js() {
     console_error_panic_hook::set_once();
     log("Wasm module initialized");
 }
+
+/// Lets JS check upfront whether this host can actually run a multi-threaded pool (it has
+/// `SharedArrayBuffer` and is cross-origin isolated) before calling `initThreadPool`, instead
+/// of finding out from a failure partway through worker startup.
+#[wasm_bindgen(js_name = isThreadingSupported)]
+pub fn is_threading_supported() -> bool {
+    threadpool_manager::is_threading_supported()
+}
+
 #[wasm_bindgen(js_name = multithreadedSum)]
-pub fn multithreaded_sum() -> i32 {
+pub fn multithreaded_sum(pool: &ThreadPoolHandle) -> i32 {
     // execute sum_mapped in the threadpool
     let v: Vec<i32> = (1..=10).collect();
-    threadpool_manager::run_in_pool(|| parallel_sum(v))
+    pool.run_in_pool(|| parallel_sum(v))
 }
 
 pub fn parallel_sum(inputs: Vec<i32>) -> i32 {
     inputs.into_par_iter().map(process_entry).sum()
 }
 
-/// Simulate some processing on each vector entry, this is always executed on a worker thread
+/// Same computation as `multithreadedSum`, but non-blocking: the sum is scheduled on the
+/// pool via `spawn_async` and JS gets back a Promise it can `await` instead of stalling the
+/// calling thread until the work finishes. On the single-threaded fallback pool the sum is
+/// computed inline before the Promise is returned.
+#[wasm_bindgen(js_name = multithreadedSumAsync)]
+pub fn multithreaded_sum_async(pool: &ThreadPoolHandle) -> Promise {
+    let v: Vec<i32> = (1..=10).collect();
+    pool.spawn_async(move || JsValue::from_f64(parallel_sum(v) as f64))
+}
+
+/// Runs a per-thread init routine on every worker in the pool and hands the resulting thread
+/// indices back to JS, so callers can confirm all workers actually participated.
+#[wasm_bindgen(js_name = broadcastInit)]
+pub fn broadcast_init(pool: &ThreadPoolHandle) -> Vec<usize> {
+    pool.broadcast_in_pool(|ctx| {
+        post_message_to_main_thread(&format!(
+            "broadcast init on thread {} of {}",
+            ctx.index(),
+            ctx.num_threads()
+        ));
+        ctx.index()
+    })
+}
+
+/// Simulate some processing on each vector entry, this is always executed on a worker thread.
+/// `run_in_pool`/`spawn_async` catch_unwind around this, so uncommenting the panic below to
+/// test failure handling won't take the rest of the pool down with it.
 fn process_entry(n: i32) -> i32 {
     let idx = current_thread_index().unwrap_or(0);
     post_message_to_main_thread(&format!("processing: {} on thread {}", n, idx));
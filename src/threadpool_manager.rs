@@ -1,22 +1,254 @@
 // this file is analogous to rayon.rs in the SDK
 
+use futures::channel::oneshot;
 use js_sys::Promise;
+use rayon::{current_thread_index, BroadcastContext};
 use spmc::{channel, Receiver, Sender};
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::future_to_promise;
 
-/// Rayon ThreadPool
-static mut THREAD_POOL: Option<rayon::ThreadPool> = None;
+/// Extract a human-readable message out of a `catch_unwind`/panic-handler payload, falling
+/// back to a generic message for payloads that aren't a `&str` or `String` (the two types
+/// `panic!` actually produces).
+fn panic_message(payload: &(dyn Any + Send + 'static)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "worker thread panicked with a non-string payload".to_string()
+    }
+}
 
-/// run an operation in the ThreadPool
-pub fn run_in_pool<OP, R>(op: OP) -> R
-where
-    OP: FnOnce() -> R + Send,
-    R: Send,
-{
-    let pool = unsafe { THREAD_POOL.as_ref().unwrap() };
-    pool.install(op)
+/// Turn a caught panic into the structured value JS sees when a Promise is rejected: the
+/// index of the pool thread the job panicked on, plus its message. `thread_index` must be
+/// captured on the worker thread where the panic actually happened - by the time this runs,
+/// `future_to_promise`'s continuation may be polling on a different thread entirely (usually
+/// the one that called `spawn_async`), where `current_thread_index()` would just be `None`.
+fn panic_to_js_value(thread_index: Option<usize>, payload: Box<dyn Any + Send>) -> JsValue {
+    let thread_index = thread_index.unwrap_or(usize::MAX);
+    let message = panic_message(payload.as_ref());
+
+    let error = js_sys::Object::new();
+    js_sys::Reflect::set(
+        &error,
+        &JsValue::from_str("threadIndex"),
+        &JsValue::from_f64(thread_index as f64),
+    )
+    .unwrap_throw();
+    js_sys::Reflect::set(
+        &error,
+        &JsValue::from_str("message"),
+        &JsValue::from_str(&message),
+    )
+    .unwrap_throw();
+    error.into()
+}
+
+/// Source of the ids handed to JS via `ThreadPoolHandle::pool_id`/`terminate_workers`, so
+/// several independently sized pools can be torn down one at a time instead of JS only ever
+/// being able to kill every pool's workers at once.
+static NEXT_POOL_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Shared state backing one worker pool: an id JS can use to address this pool specifically,
+/// the built Rayon pool, and a manual refcount. We track the refcount ourselves rather than
+/// relying solely on `Arc`'s strong count so that pool teardown is driven purely by
+/// `ThreadPoolHandle` lifetimes, regardless of how many short-lived `Arc` clones wasm-bindgen's
+/// JS glue creates in between.
+struct PoolState {
+    id: usize,
+    num_threads: usize,
+    pool: rayon::ThreadPool,
+    refcount: AtomicUsize,
+    /// Set for the `build_single_threaded_pool` fallback, whose "current thread" slot only
+    /// runs Rayon's work-stealing loop while actually inside an `install`/`broadcast`/`join`
+    /// call. `spawn_async` checks this to avoid handing a job to `pool.spawn`'s injector queue,
+    /// which nothing would ever come back around to drain.
+    runs_on_current_thread: bool,
+}
+
+impl PoolState {
+    /// Decrement the refcount; if this was the last outstanding handle, terminate this pool's
+    /// underlying JS workers and return that termination Promise, otherwise `None`.
+    fn release(&self) -> Option<Promise> {
+        if self.refcount.fetch_sub(1, Ordering::SeqCst) == 1 {
+            Some(terminate_workers(self.id))
+        } else {
+            None
+        }
+    }
+}
+
+/// A clonable, reference-counted handle to a single worker pool. `init_thread_pool` returns
+/// one of these instead of writing into a crate-global static, so an application can run
+/// several independently sized pools side by side and teardown is deterministic: the handle
+/// whose release observes the refcount hit zero is the one that tears down the JS workers.
+/// `run_in_pool`, `broadcast_in_pool`, `spawn_async`, and `exit`, which used to be free
+/// functions reaching into a `static mut`, are now methods on this handle, so reaching a pool
+/// needs no `unsafe`. The one `unsafe` block left in this module is the dereference of the raw
+/// receiver pointer in `wbg_rayon_start_worker`.
+#[wasm_bindgen]
+pub struct ThreadPoolHandle {
+    state: Option<Arc<PoolState>>,
+}
+
+impl ThreadPoolHandle {
+    fn new(num_threads: usize, pool: rayon::ThreadPool, runs_on_current_thread: bool) -> Self {
+        Self {
+            state: Some(Arc::new(PoolState {
+                id: NEXT_POOL_ID.fetch_add(1, Ordering::SeqCst),
+                num_threads,
+                pool,
+                refcount: AtomicUsize::new(1),
+                runs_on_current_thread,
+            })),
+        }
+    }
+
+    fn state(&self) -> &PoolState {
+        self.state.as_ref().unwrap_throw()
+    }
+
+    /// run an operation in this pool
+    ///
+    /// `op` runs inside `catch_unwind`, so the pool's other threads stay alive and usable even
+    /// if it panics; the caught panic is then re-raised on the calling thread, without passing
+    /// through the pool's `panic_handler`. Requires the `unwind` panic strategy - see the
+    /// `compile_error!` in `lib.rs`.
+    pub fn run_in_pool<OP, R>(&self, op: OP) -> R
+    where
+        OP: FnOnce() -> R + Send,
+        R: Send,
+    {
+        self.state()
+            .pool
+            .install(move || panic::catch_unwind(AssertUnwindSafe(op)))
+            .unwrap_or_else(|payload| panic::resume_unwind(payload))
+    }
+
+    /// Run a closure exactly once on every thread in the pool and collect one result per
+    /// thread, indexed by `ctx.index()`. Unlike `run_in_pool`/`into_par_iter`, which hand out
+    /// work items to whichever thread is free, this guarantees full participation - useful
+    /// for per-thread setup like seeding RNGs or warming caches. Note that the calling (main)
+    /// thread does not count as a pool thread and never runs `op` - except on the
+    /// single-threaded fallback pool, whose only thread is the one that built it.
+    pub fn broadcast_in_pool<OP, R>(&self, op: OP) -> Vec<R>
+    where
+        OP: Fn(BroadcastContext) -> R + Sync,
+        R: Send,
+    {
+        self.state().pool.broadcast(op)
+    }
+
+    /// Spawn a closure on this pool without blocking the calling thread, returning a JS
+    /// Promise that resolves with the closure's result once a worker finishes it.
+    ///
+    /// Unlike `run_in_pool`, the caller does not wait for `op` to complete: the task travels
+    /// back over a oneshot channel whose receiver is adapted into the returned Promise via
+    /// `future_to_promise`. As with `run_in_pool`, `op` runs inside `catch_unwind`, so a
+    /// panicking job rejects the Promise with a structured `{ threadIndex, message }` value
+    /// instead of tearing down the runtime.
+    ///
+    /// On the single-threaded fallback pool the caller does wait: `op` runs inline on the
+    /// calling thread and the returned Promise is already settled.
+    pub fn spawn_async<OP, R>(&self, op: OP) -> Promise
+    where
+        OP: FnOnce() -> R + Send + 'static,
+        R: Send + 'static + Into<JsValue>,
+    {
+        let state = self.state();
+
+        if state.runs_on_current_thread {
+            // The fallback pool has no dedicated worker thread draining `pool.spawn`'s
+            // injector queue - its "current thread" slot only runs Rayon's work-stealing loop
+            // while actually inside an `install`/`broadcast`/`join` call, so a job handed to
+            // `pool.spawn` here would sit queued forever. Run it inline via `install` instead.
+            let result = state.pool.install(move || panic::catch_unwind(AssertUnwindSafe(op)));
+            let thread_index = current_thread_index();
+            return future_to_promise(async move {
+                match result {
+                    Ok(result) => Ok(result.into()),
+                    Err(payload) => Err(panic_to_js_value(thread_index, payload)),
+                }
+            });
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        state.pool.spawn(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(op));
+            // capture the index here, on the worker thread that actually ran (and possibly
+            // panicked in) `op` - not in the continuation below, which runs wherever the
+            // Promise executor polls the future
+            let thread_index = current_thread_index();
+            // the receiving end may already be gone if JS discarded the Promise; ignore that case
+            let _ = sender.send((thread_index, result));
+        });
+
+        future_to_promise(async move {
+            match receiver.await {
+                Ok((_, Ok(result))) => Ok(result.into()),
+                Ok((thread_index, Err(payload))) => Err(panic_to_js_value(thread_index, payload)),
+                Err(_) => Err(JsValue::from_str(
+                    "spawn_async: task was dropped before it produced a result",
+                )),
+            }
+        })
+    }
+}
+
+#[wasm_bindgen]
+impl ThreadPoolHandle {
+    /// expose getter for number of threads to JS
+    #[wasm_bindgen(js_name = numThreads)]
+    pub fn num_threads(&self) -> usize {
+        self.state().num_threads
+    }
+
+    /// Id JS should key its worker bookkeeping by, so `terminateWorkers` (which only ever
+    /// gets this id, not a whole handle) tears down this pool's workers specifically.
+    #[wasm_bindgen(js_name = poolId)]
+    pub fn pool_id(&self) -> usize {
+        self.state().id
+    }
+
+    /// Hand out another handle to the same pool. JS can hold as many clones as it likes;
+    /// the pool only tears down once every clone has been exited or dropped.
+    #[wasm_bindgen(js_name = clone)]
+    pub fn js_clone(&self) -> ThreadPoolHandle {
+        let state = self.state.as_ref().unwrap_throw();
+        state.refcount.fetch_add(1, Ordering::SeqCst);
+        ThreadPoolHandle {
+            state: Some(Arc::clone(state)),
+        }
+    }
+
+    /// called by JS to terminate workers and tear down the pool when it is no longer
+    /// needed. Only the clone that observes the refcount drop to zero actually terminates
+    /// the workers; other outstanding clones keep the pool alive.
+    #[wasm_bindgen(js_name = exit)]
+    pub fn exit(mut self) -> Promise {
+        match self.state.take().and_then(|state| state.release()) {
+            Some(promise) => promise,
+            None => Promise::resolve(&JsValue::UNDEFINED),
+        }
+    }
 }
- 
+
+impl Drop for ThreadPoolHandle {
+    fn drop(&mut self) {
+        if let Some(state) = self.state.take() {
+            // fire-and-forget: there's no caller left to hand a termination Promise to once
+            // the handle is merely being garbage-collected rather than explicitly exited
+            let _ = state.release();
+        }
+    }
+}
+
 /// Wraps SPMC channel used to send Rayon ThreadBuilders to JS workers
 #[wasm_bindgen]
 pub struct PoolBuilder {
@@ -51,31 +283,127 @@ impl PoolBuilder {
         &self.receiver
     }
 
-    /// Build the Rayon pool and send each ThreadBuilder over the SPMC channel
-    pub fn build(&mut self) {
-        unsafe {
-            THREAD_POOL = Some(
-                rayon::ThreadPoolBuilder::new()
-                    .num_threads(self.num_threads)
-                    .spawn_handler(move |thread| {
-                        // spawn Rayon threads by sending the ThreadBuilder over the SPMC channel to be processed by a JS worker
-                        self.sender.send(thread).unwrap_throw();
-                        Ok(())
-                    })
-                    .build()
-                    .unwrap_throw(),
-            )
-        }
+    /// Build the Rayon pool and send each ThreadBuilder over the SPMC channel, returning a
+    /// clonable handle to the newly built pool instead of writing into a process-global static.
+    pub fn build(&mut self) -> ThreadPoolHandle {
+        let num_threads = self.num_threads;
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .spawn_handler(move |thread| {
+                // spawn Rayon threads by sending the ThreadBuilder over the SPMC channel to be processed by a JS worker
+                self.sender.send(thread).unwrap_throw();
+                Ok(())
+            })
+            .panic_handler(|payload| {
+                // `run_in_pool`/`spawn_async` already catch_unwind the jobs they schedule and
+                // `broadcast_in_pool` propagates panics back to its caller, so this only sees a
+                // panic that escapes a job spawned onto the pool uncaught; log it and let the
+                // thread carry on instead of letting Rayon abort it.
+                let thread_index = current_thread_index().unwrap_or(usize::MAX);
+                crate::log(&format!(
+                    "rayon worker {} panicked: {}",
+                    thread_index,
+                    panic_message(payload.as_ref())
+                ));
+            })
+            .build()
+            .unwrap_throw();
+
+        ThreadPoolHandle::new(num_threads, pool, false)
     }
 }
 
-/// Entrypoint - Called by JS node-backend to initialize the thread pool with a specified number of threads 
+/// Returns true when the host exposes `SharedArrayBuffer` and the page is cross-origin
+/// isolated - the two preconditions the `atomics`/`bulk-memory` build actually needs in order
+/// to hand linear memory to worker threads. False covers both an older browser and a page
+/// that forgot the `Cross-Origin-Opener-Policy`/`Cross-Origin-Embedder-Policy` headers.
+pub fn is_threading_supported() -> bool {
+    let has_shared_array_buffer =
+        js_sys::Reflect::has(&js_sys::global(), &JsValue::from_str("SharedArrayBuffer"))
+            .unwrap_or(false);
+    let cross_origin_isolated = web_sys::window()
+        .map(|window| window.cross_origin_isolated())
+        .or_else(|| {
+            js_sys::global()
+                .dyn_into::<web_sys::WorkerGlobalScope>()
+                .ok()
+                .map(|scope| scope.cross_origin_isolated())
+        })
+        // neither a window nor a worker global scope (e.g. node) doesn't gate on this flag
+        .unwrap_or(true);
+
+    has_shared_array_buffer && cross_origin_isolated
+}
+
+/// Build a pool that runs entirely on the calling thread via `use_current_thread` - no Worker
+/// spawning, no SPMC channel - for hosts that fail `is_threading_supported`. `multithreadedSum`
+/// and friends still produce correct results through it, just sequentially instead of in parallel.
+fn build_single_threaded_pool() -> rayon::ThreadPool {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .use_current_thread()
+        .panic_handler(|payload| {
+            crate::log(&format!(
+                "rayon worker panicked: {}",
+                panic_message(payload.as_ref())
+            ));
+        })
+        .build()
+        .unwrap_throw()
+}
+
+/// Entrypoint - Called by JS node-backend to initialize the thread pool with a specified number of threads
 #[wasm_bindgen(js_name = initThreadPool)]
 pub fn init_thread_pool(num_threads: usize) -> Promise {
-    // Create a PoolBuilder with an SPMC channel for distributing ThreadBuilders to workers.
-    // The PoolBuilder exposes a receiver pointer that JavaScript passes to each spawned worker.
-    // Each worker then calls wbg_rayon_start_worker with this receiver to join the thread pool.
-    start_workers(wasm_bindgen::memory(), PoolBuilder::new(num_threads))
+    if !is_threading_supported() {
+        // No SharedArrayBuffer / cross-origin isolation: fall back to a pool that runs on the
+        // calling thread rather than failing to spawn Workers it has no way to create.
+        let handle = ThreadPoolHandle::new(1, build_single_threaded_pool(), true);
+        return Promise::resolve(&JsValue::from(handle));
+    }
+
+    // Create a PoolBuilder with an SPMC channel for distributing ThreadBuilders to workers,
+    // and build the Rayon pool behind a clonable handle right away. The PoolBuilder still
+    // exposes a receiver pointer that JavaScript passes to each spawned worker, and each
+    // worker calls wbg_rayon_start_worker with that receiver to join the pool; once they're
+    // all up, start_workers resolves the returned Promise with `handle`.
+    let mut builder = PoolBuilder::new(num_threads);
+    let handle = builder.build();
+    start_workers(wasm_bindgen::memory(), builder, handle)
+}
+
+/// Fallback thread count used when the host exposes no `navigator.hardwareConcurrency`
+/// (e.g. an older browser or an unusual worker embedding).
+const DEFAULT_THREAD_COUNT: usize = 4;
+
+/// Number of logical cores the current environment reports, used to size the pool when the
+/// caller doesn't know (or doesn't want to guess) how many threads to ask for.
+///
+/// Tries the window's `navigator` first, then falls back to a worker global scope's
+/// `navigator` (this crate's workers run on a node backend as well as in browsers), and
+/// finally `DEFAULT_THREAD_COUNT` when neither is available or the reported count is missing.
+/// Always clamped to at least 1.
+fn detect_hardware_concurrency() -> usize {
+    let cores = web_sys::window()
+        .map(|window| window.navigator().hardware_concurrency())
+        .or_else(|| {
+            js_sys::global()
+                .dyn_into::<web_sys::WorkerGlobalScope>()
+                .ok()
+                .map(|scope| scope.navigator().hardware_concurrency())
+        })
+        // a navigator without `hardwareConcurrency` reads as NaN here
+        .filter(|cores| cores.is_finite() && *cores >= 1.0)
+        .unwrap_or(DEFAULT_THREAD_COUNT as f64);
+
+    (cores as usize).max(1)
+}
+
+/// Entrypoint - Called by JS to initialize the thread pool sized to the environment's
+/// logical core count, instead of requiring JS to pass (and possibly mis-guess) a count.
+#[wasm_bindgen(js_name = initThreadPoolAuto)]
+pub fn init_thread_pool_auto() -> Promise {
+    init_thread_pool(detect_hardware_concurrency())
 }
 
 /// Called by a JS worker thread to join the Rayon ThreadPool
@@ -86,27 +414,17 @@ where
 {
     // retrieve the SPMC receiver, then use it to receive a Rayon ThreadBuilder
     let receiver = unsafe { &*receiver };
-                                                                    
+
     // run the ThreadBuilder, this will continuously poll for tasks from Rayon's work-stealing queues and block until the pool is shut down
     receiver.recv().unwrap_throw().run();
 }
 
-/// called by JS to terminate workers and clear the pool when it is no longer needed
-#[wasm_bindgen(js_name = exitThreadPool)]
-pub fn exit_thread_pool() -> Promise {
-    unsafe {
-        let promise = terminate_workers();
-        THREAD_POOL = None;
-        promise
-    }
-}
-
 /// FFI bindings to JS functions that spawn and terminate workers
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen(js_name = startWorkers)]
-    fn start_workers(memory: JsValue, builder: PoolBuilder) -> Promise;
+    fn start_workers(memory: JsValue, builder: PoolBuilder, handle: ThreadPoolHandle) -> Promise;
 
     #[wasm_bindgen(js_name = terminateWorkers)]
-    fn terminate_workers() -> Promise;
+    fn terminate_workers(pool_id: usize) -> Promise;
 }
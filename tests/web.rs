@@ -2,7 +2,13 @@
 #![cfg(target_arch = "wasm32")]
 
 extern crate wasm_bindgen_test;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
 use wasm_bindgen_test::*;
+use wasm_worker_threads::{
+    broadcast_init, init_thread_pool, init_thread_pool_auto, is_threading_supported,
+    multithreaded_sum, multithreaded_sum_async,
+};
 
 // enable running tests in a browser
 wasm_bindgen_test_configure!(run_in_browser);
@@ -11,3 +17,107 @@ wasm_bindgen_test_configure!(run_in_browser);
 fn pass() {
     assert_eq!(1 + 1, 2);
 }
+
+// The test page isn't cross-origin isolated, so `init_thread_pool` takes the single-threaded
+// fallback path (chunk0-6) rather than spawning real Workers - which is exactly what lets this
+// run without the JS-side `startWorkers`/`terminateWorkers` glue this crate also depends on.
+#[wasm_bindgen_test]
+async fn spawn_async_resolves_with_the_computed_sum() {
+    let handle = JsFuture::from(init_thread_pool(1))
+        .await
+        .expect("init_thread_pool should resolve");
+    let handle: wasm_worker_threads::ThreadPoolHandle = handle.unchecked_into();
+
+    let sum = JsFuture::from(multithreaded_sum_async(&handle))
+        .await
+        .expect("spawn_async should resolve with the sum");
+    assert_eq!(sum.as_f64().unwrap() as i32, 55);
+
+    // avoid running the handle's Drop impl, which would call the JS `terminateWorkers` glue
+    // that this standalone test page doesn't provide
+    std::mem::forget(handle);
+}
+
+#[wasm_bindgen_test]
+async fn broadcast_init_returns_one_index_per_pool_thread() {
+    let handle = JsFuture::from(init_thread_pool(1))
+        .await
+        .expect("init_thread_pool should resolve");
+    let handle: wasm_worker_threads::ThreadPoolHandle = handle.unchecked_into();
+
+    let indices = broadcast_init(&handle);
+    assert_eq!(indices.len(), handle.num_threads());
+
+    std::mem::forget(handle);
+}
+
+#[wasm_bindgen_test]
+async fn init_thread_pool_auto_sizes_the_pool_to_at_least_one_thread() {
+    let handle = JsFuture::from(init_thread_pool_auto())
+        .await
+        .expect("init_thread_pool_auto should resolve");
+    let handle: wasm_worker_threads::ThreadPoolHandle = handle.unchecked_into();
+
+    assert!(handle.num_threads() >= 1);
+
+    std::mem::forget(handle);
+}
+
+#[wasm_bindgen_test]
+async fn cloned_handle_is_independently_usable() {
+    let handle = JsFuture::from(init_thread_pool(1))
+        .await
+        .expect("init_thread_pool should resolve");
+    let handle: wasm_worker_threads::ThreadPoolHandle = handle.unchecked_into();
+
+    let clone = handle.js_clone();
+    assert_eq!(clone.num_threads(), handle.num_threads());
+
+    let sum = JsFuture::from(multithreaded_sum_async(&clone))
+        .await
+        .expect("spawn_async on the clone should resolve with the sum");
+    assert_eq!(sum.as_f64().unwrap() as i32, 55);
+
+    // deliberately not calling .exit() (or letting these drop): the last handle released
+    // would call the JS `terminateWorkers` glue this standalone test page doesn't provide
+    std::mem::forget(handle);
+    std::mem::forget(clone);
+}
+
+#[wasm_bindgen_test]
+async fn spawn_async_rejects_with_a_structured_error_on_panic() {
+    let handle = JsFuture::from(init_thread_pool(1))
+        .await
+        .expect("init_thread_pool should resolve");
+    let handle: wasm_worker_threads::ThreadPoolHandle = handle.unchecked_into();
+
+    let rejection = JsFuture::from(handle.spawn_async::<_, JsValue>(|| panic!("boom")))
+        .await
+        .expect_err("a panicking job should reject the Promise");
+
+    let message = js_sys::Reflect::get(&rejection, &JsValue::from_str("message")).unwrap();
+    assert_eq!(message.as_string().unwrap(), "boom");
+
+    let thread_index = js_sys::Reflect::get(&rejection, &JsValue::from_str("threadIndex")).unwrap();
+    assert!(thread_index.as_f64().unwrap() >= 0.0);
+
+    std::mem::forget(handle);
+}
+
+// This test page lacks cross-origin isolation, so `is_threading_supported` is expected to
+// report `false` here and `init_thread_pool` to take the single-threaded fallback path - the
+// same fallback every other test in this file already relies on.
+#[wasm_bindgen_test]
+async fn single_threaded_fallback_reports_unsupported_and_still_computes_the_sum() {
+    assert!(!is_threading_supported());
+
+    let handle = JsFuture::from(init_thread_pool(1))
+        .await
+        .expect("init_thread_pool should resolve via the single-threaded fallback");
+    let handle: wasm_worker_threads::ThreadPoolHandle = handle.unchecked_into();
+    assert_eq!(handle.num_threads(), 1);
+
+    assert_eq!(multithreaded_sum(&handle), 55);
+
+    std::mem::forget(handle);
+}